@@ -8,7 +8,12 @@ use sucds::{EliasFano, Searial};
 
 /// Uses EliasFano to hold a list of integers. It provides really good compression with the
 /// capability to access its elements without decoding it.
-#[derive(Clone, PartialEq, Eq, Default)]
+///
+/// Deliberately doesn't derive `Default`: a default-constructed `EliasFano` never goes through
+/// [`IntegerList::new`]/[`IntegerList::new_pre_sorted`]/[`IntegerList::from_bytes`], so it never
+/// gets `enable_rank()` called on it, and `contains`/`rank`/`successor`/`predecessor` would panic
+/// on it.
+#[derive(Clone, PartialEq, Eq)]
 pub struct IntegerList(pub EliasFano);
 
 impl Deref for IntegerList {
@@ -34,7 +39,11 @@ impl IntegerList {
     ///
     /// Returns an error if the list is empty or not pre-sorted.
     pub fn new<T: AsRef<[usize]>>(list: T) -> Result<Self, EliasFanoError> {
-        Ok(Self(EliasFano::from_ints(list.as_ref()).map_err(|_| EliasFanoError::InvalidInput)?))
+        Ok(Self(
+            EliasFano::from_ints(list.as_ref())
+                .map(|ef| ef.enable_rank())
+                .map_err(|_| EliasFanoError::InvalidInput)?,
+        ))
     }
 
     // Creates an IntegerList from a pre-sorted list of integers. `usize` is safe to use since
@@ -46,6 +55,7 @@ impl IntegerList {
     pub fn new_pre_sorted<T: AsRef<[usize]>>(list: T) -> Self {
         Self(
             EliasFano::from_ints(list.as_ref())
+                .map(|ef| ef.enable_rank())
                 .expect("IntegerList must be pre-sorted and non-empty."),
         )
     }
@@ -67,7 +77,48 @@ impl IntegerList {
 
     /// Deserializes a sequence of bytes into a proper [`IntegerList`].
     pub fn from_bytes(data: &[u8]) -> Result<Self, EliasFanoError> {
-        Ok(Self(EliasFano::deserialize_from(data).map_err(|_| EliasFanoError::FailedDeserialize)?))
+        Ok(Self(
+            EliasFano::deserialize_from(data)
+                .map(|ef| ef.enable_rank())
+                .map_err(|_| EliasFanoError::FailedDeserialize)?,
+        ))
+    }
+
+    /// Decodes `data` and ties the result to its lifetime, e.g. bytes backed by a memory-mapped
+    /// database page that outlives the query, so the decoded index and the page it was read from
+    /// can be handed around together instead of managing the page slice separately.
+    ///
+    /// Note this doesn't avoid the decode cost of [`Self::from_bytes`]: `sucds::EliasFano` has no
+    /// API for answering rank/select queries straight out of undecoded bytes, so the same
+    /// allocate-and-copy pass still happens here, up front, where its errors can be handled
+    /// through the returned `Result` rather than surfacing as a panic from a later query.
+    pub fn from_bytes_borrowed(data: &[u8]) -> Result<IntegerListRef<'_>, EliasFanoError> {
+        Ok(IntegerListRef { list: Self::from_bytes(data)?, data })
+    }
+
+    /// Returns `true` if `value` is stored in the list.
+    pub fn contains(&self, value: u64) -> bool {
+        self.successor(value) == Some(value)
+    }
+
+    /// Returns the number of stored integers strictly less than `value`.
+    pub fn rank(&self, value: u64) -> usize {
+        self.0.rank(value as usize)
+    }
+
+    /// Returns the `n`-th smallest stored integer, or `None` if out of bounds.
+    pub fn select(&self, n: usize) -> Option<u64> {
+        (n < self.len()).then(|| self.0.select(n) as u64)
+    }
+
+    /// Returns the smallest stored integer greater than or equal to `value`.
+    pub fn successor(&self, value: u64) -> Option<u64> {
+        self.0.successor(value as usize).map(|v| v as u64)
+    }
+
+    /// Returns the largest stored integer less than or equal to `value`.
+    pub fn predecessor(&self, value: u64) -> Option<u64> {
+        self.0.predecessor(value as usize).map(|v| v as u64)
     }
 
     /// Iterates over two lists of integers and creates an intersection.
@@ -100,6 +151,110 @@ impl IntegerList {
             Some(Self::new_pre_sorted(result))
         }
     }
+
+    /// Iterates over two lists of integers and creates their union.
+    ///
+    /// Returns `None` if the resulting list is empty.
+    pub fn union(&self, other: &Self) -> Option<Self> {
+        let mut result = Vec::with_capacity(self.len() + other.len());
+
+        let (mut this_iter, mut other_iter) = (self.iter(0), other.iter(0));
+        let (mut maybe_this_int, mut maybe_other_int) = (this_iter.next(), other_iter.next());
+        while let (Some(this_int), Some(other_int)) = (maybe_this_int, maybe_other_int) {
+            match this_int.cmp(&other_int) {
+                Ordering::Equal => {
+                    result.push(this_int);
+                    maybe_this_int = this_iter.next();
+                    maybe_other_int = other_iter.next();
+                }
+                Ordering::Less => {
+                    result.push(this_int);
+                    maybe_this_int = this_iter.next();
+                }
+                Ordering::Greater => {
+                    result.push(other_int);
+                    maybe_other_int = other_iter.next();
+                }
+            };
+        }
+        result.extend(maybe_this_int.into_iter().chain(this_iter));
+        result.extend(maybe_other_int.into_iter().chain(other_iter));
+
+        if result.is_empty() {
+            None
+        } else {
+            Some(Self::new_pre_sorted(result))
+        }
+    }
+
+    /// Iterates over two lists of integers and creates the difference, i.e. the elements of
+    /// `self` that are not present in `other`.
+    ///
+    /// Returns `None` if the resulting list is empty.
+    pub fn difference(&self, other: &Self) -> Option<Self> {
+        let mut result = Vec::with_capacity(self.len());
+
+        let (mut this_iter, mut other_iter) = (self.iter(0), other.iter(0));
+        let (mut maybe_this_int, mut maybe_other_int) = (this_iter.next(), other_iter.next());
+        while let Some(this_int) = maybe_this_int {
+            match maybe_other_int {
+                Some(other_int) if other_int < this_int => {
+                    maybe_other_int = other_iter.next();
+                }
+                Some(other_int) if other_int == this_int => {
+                    maybe_this_int = this_iter.next();
+                    maybe_other_int = other_iter.next();
+                }
+                _ => {
+                    result.push(this_int);
+                    maybe_this_int = this_iter.next();
+                }
+            }
+        }
+
+        if result.is_empty() {
+            None
+        } else {
+            Some(Self::new_pre_sorted(result))
+        }
+    }
+}
+
+/// An [`IntegerList`] tied to the lifetime of the encoded bytes it was decoded from, returned by
+/// [`IntegerList::from_bytes_borrowed`]. Lets table decoders in the DB layer hold onto a history
+/// index alongside the memory-mapped page it came from, instead of threading the page slice back
+/// through every later lookup.
+#[derive(Debug)]
+pub struct IntegerListRef<'a> {
+    list: IntegerList,
+    data: &'a [u8],
+}
+
+impl<'a> IntegerListRef<'a> {
+    /// Returns the encoded bytes this view was built from.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Iterates over the stored integers, starting from `start`.
+    pub fn iter(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        self.list.iter(start)
+    }
+
+    /// Returns `true` if `value` is stored in the list.
+    pub fn contains(&self, value: u64) -> bool {
+        self.list.contains(value)
+    }
+
+    /// Returns the number of stored integers strictly less than `value`.
+    pub fn rank(&self, value: u64) -> usize {
+        self.list.rank(value)
+    }
+
+    /// Returns the `n`-th smallest stored integer, or `None` if out of bounds.
+    pub fn select(&self, n: usize) -> Option<u64> {
+        self.list.select(n)
+    }
 }
 
 macro_rules! impl_uint {
@@ -108,7 +263,11 @@ macro_rules! impl_uint {
             impl From<Vec<$w>> for IntegerList {
                 fn from(v: Vec<$w>) -> Self {
                     let v: Vec<usize> = v.iter().map(|v| *v as usize).collect();
-                    Self(EliasFano::from_ints(v.as_slice()).expect("could not create list."))
+                    Self(
+                        EliasFano::from_ints(v.as_slice())
+                            .map(|ef| ef.enable_rank())
+                            .expect("could not create list."),
+                    )
                 }
             }
         )+
@@ -122,12 +281,19 @@ impl Serialize for IntegerList {
     where
         S: Serializer,
     {
-        let vec = self.0.iter(0).collect::<Vec<usize>>();
-        let mut seq = serializer.serialize_seq(Some(self.len()))?;
-        for e in vec {
-            seq.serialize_element(&e)?;
+        // Human-readable formats (e.g. JSON) get the expanded integer sequence so the value is
+        // debuggable; binary formats get the compact Elias-Fano-encoded bytes directly, instead
+        // of re-expanding (and losing) the compression `to_bytes` gives us.
+        if serializer.is_human_readable() {
+            let vec = self.0.iter(0).collect::<Vec<usize>>();
+            let mut seq = serializer.serialize_seq(Some(self.len()))?;
+            for e in vec {
+                seq.serialize_element(&e)?;
+            }
+            seq.end()
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
         }
-        seq.end()
     }
 }
 
@@ -136,7 +302,7 @@ impl<'de> Visitor<'de> for IntegerListVisitor {
     type Value = IntegerList;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        formatter.write_str("a usize array")
+        formatter.write_str("a usize array or a byte array")
     }
 
     fn visit_seq<E>(self, mut seq: E) -> Result<Self::Value, E::Error>
@@ -150,6 +316,21 @@ impl<'de> Visitor<'de> for IntegerListVisitor {
 
         IntegerList::new(list).map_err(|_| serde::de::Error::invalid_value(Unexpected::Seq, &self))
     }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        IntegerList::from_bytes(v)
+            .map_err(|_| serde::de::Error::invalid_value(Unexpected::Bytes(v), &self))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visit_bytes(&v)
+    }
 }
 
 impl<'de> Deserialize<'de> for IntegerList {
@@ -157,10 +338,46 @@ impl<'de> Deserialize<'de> for IntegerList {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_byte_buf(IntegerListVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_seq(IntegerListVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(IntegerListVisitor)
+        }
     }
 }
 
+/// Deserializes Elias-Fano-encoded bytes borrowed from the deserializer, e.g. an mdbx value
+/// backed by a memory-mapped page, into an [`IntegerListRef`] tied to that page's lifetime.
+/// Intended for use as `#[serde(deserialize_with = "deserialize_borrowed")]` on table decoders in
+/// the DB layer. Requires a `Deserializer` that can hand back a truly borrowed byte slice; one
+/// that can't (e.g. reading through a non-borrowing `std::io::Read` adapter) fails to
+/// deserialize rather than falling back to an owned copy.
+#[cfg(feature = "borrowed-serde")]
+pub fn deserialize_borrowed<'de, D>(deserializer: D) -> Result<IntegerListRef<'de>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct BorrowedBytesVisitor;
+
+    impl<'de> Visitor<'de> for BorrowedBytesVisitor {
+        type Value = IntegerListRef<'de>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            formatter.write_str("a borrowed byte array")
+        }
+
+        fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            IntegerList::from_bytes_borrowed(v)
+                .map_err(|_| serde::de::Error::invalid_value(Unexpected::Bytes(v), &self))
+        }
+    }
+
+    deserializer.deserialize_bytes(BorrowedBytesVisitor)
+}
+
 #[cfg(any(test, feature = "arbitrary"))]
 use arbitrary::{Arbitrary, Unstructured};
 
@@ -169,7 +386,146 @@ impl<'a> Arbitrary<'a> for IntegerList {
     fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self, arbitrary::Error> {
         let mut nums: Vec<usize> = Vec::arbitrary(u)?;
         nums.sort();
-        Ok(Self(EliasFano::from_ints(&nums).map_err(|_| arbitrary::Error::IncorrectFormat)?))
+        Ok(Self(
+            EliasFano::from_ints(&nums)
+                .map(|ef| ef.enable_rank())
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?,
+        ))
+    }
+}
+
+/// Uses EliasFano to hold an arbitrary (non-monotonic, possibly repeated) sequence of integers,
+/// by encoding the running prefix sum of the sequence instead of the sequence itself. This gives
+/// [`IntegerList`]'s access-without-decode property to columns that aren't already strictly
+/// increasing, e.g. gas used per block or log counts per block.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct PrefixSummedIntegerList(pub EliasFano);
+
+impl fmt::Debug for PrefixSummedIntegerList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vec: Vec<u64> = (0..self.0.len()).map(|i| self.get(i).expect("in bounds")).collect();
+        write!(f, "PrefixSummedIntegerList {:?}", vec)
+    }
+}
+
+impl PrefixSummedIntegerList {
+    /// Creates a [`PrefixSummedIntegerList`] from an arbitrary (non-monotonic, possibly repeated)
+    /// list of integers, by Elias-Fano encoding the running prefix sum of `ints`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the list is empty.
+    pub fn new(ints: &[u64]) -> Result<Self, EliasFanoError> {
+        if ints.is_empty() {
+            return Err(EliasFanoError::InvalidInput)
+        }
+
+        let mut sum = 0u64;
+        let prefix_sums: Vec<usize> = ints
+            .iter()
+            .map(|value| {
+                sum += value;
+                sum as usize
+            })
+            .collect();
+
+        Ok(Self(EliasFano::from_ints(&prefix_sums).map_err(|_| EliasFanoError::InvalidInput)?))
+    }
+
+    /// Returns the value originally stored at `index`, or `None` if out of bounds.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.0.len() {
+            return None
+        }
+
+        let current = self.0.select(index) as u64;
+        let previous = if index == 0 { 0 } else { self.0.select(index - 1) as u64 };
+        Some(current - previous)
+    }
+
+    /// Returns the sum of all originally stored values, i.e. the final prefix sum.
+    pub fn sum(&self) -> u64 {
+        self.0.select(self.0.len() - 1) as u64
+    }
+
+    /// Serializes a [`PrefixSummedIntegerList`] into a sequence of bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(self.0.size_in_bytes());
+        self.0.serialize_into(&mut vec).expect("not able to encode integer list.");
+        vec
+    }
+
+    /// Deserializes a sequence of bytes into a proper [`PrefixSummedIntegerList`].
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EliasFanoError> {
+        Ok(Self(EliasFano::deserialize_from(data).map_err(|_| EliasFanoError::FailedDeserialize)?))
+    }
+}
+
+impl Serialize for PrefixSummedIntegerList {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let vec: Vec<u64> = (0..self.0.len()).map(|i| self.get(i).expect("in bounds")).collect();
+            let mut seq = serializer.serialize_seq(Some(vec.len()))?;
+            for e in vec {
+                seq.serialize_element(&e)?;
+            }
+            seq.end()
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+struct PrefixSummedIntegerListVisitor;
+impl<'de> Visitor<'de> for PrefixSummedIntegerListVisitor {
+    type Value = PrefixSummedIntegerList;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a u64 array or a byte array")
+    }
+
+    fn visit_seq<E>(self, mut seq: E) -> Result<Self::Value, E::Error>
+    where
+        E: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+
+        PrefixSummedIntegerList::new(&list)
+            .map_err(|_| serde::de::Error::invalid_value(Unexpected::Seq, &self))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        PrefixSummedIntegerList::from_bytes(v)
+            .map_err(|_| serde::de::Error::invalid_value(Unexpected::Bytes(v), &self))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visit_bytes(&v)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrefixSummedIntegerList {
+    fn deserialize<D>(deserializer: D) -> Result<PrefixSummedIntegerList, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_seq(PrefixSummedIntegerListVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(PrefixSummedIntegerListVisitor)
+        }
     }
 }
 
@@ -204,6 +560,45 @@ mod test {
         assert_eq!(IntegerList::from_bytes(&blist).unwrap(), ef_list)
     }
 
+    #[test]
+    fn test_integer_list_from_bytes_borrowed() {
+        let original_list = [2, 4, 6, 8, 10];
+        let ef_list = IntegerList::new(original_list).unwrap();
+
+        let blist = ef_list.to_bytes();
+        let ef_list_ref = IntegerList::from_bytes_borrowed(&blist).unwrap();
+
+        assert_eq!(ef_list_ref.as_bytes(), blist.as_slice());
+        assert_eq!(ef_list_ref.iter(0).collect::<Vec<usize>>(), original_list);
+        assert!(ef_list_ref.contains(6));
+        assert_eq!(ef_list_ref.rank(6), 2);
+        assert_eq!(ef_list_ref.select(0), Some(2));
+    }
+
+    #[test]
+    fn test_integer_list_queries() {
+        let list = IntegerList::new([2, 4, 6, 8, 10]).unwrap();
+
+        assert!(list.contains(6));
+        assert!(!list.contains(7));
+
+        assert_eq!(list.rank(6), 2);
+        assert_eq!(list.rank(7), 3);
+        assert_eq!(list.rank(2), 0);
+
+        assert_eq!(list.select(0), Some(2));
+        assert_eq!(list.select(4), Some(10));
+        assert_eq!(list.select(5), None);
+
+        assert_eq!(list.successor(5), Some(6));
+        assert_eq!(list.successor(6), Some(6));
+        assert_eq!(list.successor(11), None);
+
+        assert_eq!(list.predecessor(5), Some(4));
+        assert_eq!(list.predecessor(6), Some(6));
+        assert_eq!(list.predecessor(1), None);
+    }
+
     #[test]
     fn test_integer_list_intersection() {
         // Empty intersection of non-empty lists
@@ -225,6 +620,40 @@ mod test {
         assert_eq!(a.intersection(&b), Some(a));
     }
 
+    #[test]
+    fn test_integer_list_union() {
+        // Union of disjoint lists
+        let a = IntegerList::new([1, 2, 3]).unwrap();
+        let b = IntegerList::new([4, 5, 6]).unwrap();
+        assert_eq!(a.union(&b), Some(IntegerList::new([1, 2, 3, 4, 5, 6]).unwrap()));
+
+        // Union with overlap
+        let a = IntegerList::new([2, 3, 4]).unwrap();
+        let b = IntegerList::new([3, 4, 5]).unwrap();
+        assert_eq!(a.union(&b), Some(IntegerList::new([2, 3, 4, 5]).unwrap()));
+
+        // Union of a list with itself
+        let a = IntegerList::new([1, 2, 3]).unwrap();
+        assert_eq!(a.union(&a), Some(a));
+    }
+
+    #[test]
+    fn test_integer_list_difference() {
+        // Difference of disjoint lists
+        let a = IntegerList::new([1, 2, 3]).unwrap();
+        let b = IntegerList::new([4, 5, 6]).unwrap();
+        assert_eq!(a.difference(&b), Some(a));
+
+        // Difference with overlap
+        let a = IntegerList::new([2, 3, 4]).unwrap();
+        let b = IntegerList::new([3, 4, 5]).unwrap();
+        assert_eq!(a.difference(&b), Some(IntegerList::new([2]).unwrap()));
+
+        // Difference of a list with itself is empty
+        let a = IntegerList::new([1, 2, 3]).unwrap();
+        assert_eq!(a.difference(&a), None);
+    }
+
     #[test]
     fn serde_serialize_deserialize() {
         let original_list = [1, 2, 3];
@@ -234,4 +663,24 @@ mod test {
         let serde_ef_list = serde_json::from_str::<IntegerList>(&serde_out).unwrap();
         assert_eq!(serde_ef_list, ef_list);
     }
+
+    #[test]
+    fn test_prefix_summed_integer_list() {
+        let original_list = [5u64, 0, 3, 0, 0, 7];
+        let list = PrefixSummedIntegerList::new(&original_list).unwrap();
+
+        let decoded: Vec<u64> = (0..original_list.len()).map(|i| list.get(i).unwrap()).collect();
+        assert_eq!(decoded, original_list);
+        assert_eq!(list.get(original_list.len()), None);
+        assert_eq!(list.sum(), original_list.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_prefix_summed_integer_list_serialization() {
+        let original_list = [5u64, 0, 3, 0, 0, 7];
+        let list = PrefixSummedIntegerList::new(&original_list).unwrap();
+
+        let blist = list.to_bytes();
+        assert_eq!(PrefixSummedIntegerList::from_bytes(&blist).unwrap(), list)
+    }
 }